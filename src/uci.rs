@@ -0,0 +1,447 @@
+//! Typed representations of the UCI protocol.
+//!
+//! This is shared by the proxy and the parser so that both inspect the same
+//! `GuiCommand` / `EngineMessage` variants instead of re-tokenizing raw lines
+//! with regexes and `split_whitespace` ad hoc. `parse` turns a raw protocol
+//! line into the matching variant; `Display` renders it back to the wire
+//! format.
+
+use std::fmt;
+
+/// A command sent from the GUI to the engine.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GuiCommand {
+    Uci,
+    UciNewGame,
+    IsReady,
+    Quit,
+    SetOption {
+        name: String,
+        value: Option<String>,
+    },
+    Position {
+        startpos: bool,
+        fen: Option<String>,
+        moves: Vec<String>,
+    },
+    Go(GoParams),
+}
+
+/// Search limits carried by a `go` command.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GoParams {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub movetime: Option<u64>,
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub infinite: bool,
+}
+
+impl GuiCommand {
+    pub fn parse(line: &str) -> Option<Self> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match *tokens.first()? {
+            "uci" => Some(GuiCommand::Uci),
+            "ucinewgame" => Some(GuiCommand::UciNewGame),
+            "isready" => Some(GuiCommand::IsReady),
+            "quit" => Some(GuiCommand::Quit),
+            "setoption" => parse_setoption(&tokens),
+            "position" => parse_position(&tokens),
+            "go" => Some(GuiCommand::Go(parse_go(&tokens))),
+            _ => None,
+        }
+    }
+}
+
+fn parse_setoption(tokens: &[&str]) -> Option<GuiCommand> {
+    // setoption name <id> [value <x>]
+    if tokens.get(1) != Some(&"name") {
+        return None;
+    }
+    let value_idx = tokens.iter().position(|&t| t == "value");
+    let name_end = value_idx.unwrap_or(tokens.len());
+    let name = tokens[2..name_end].join(" ");
+    let value = value_idx.map(|i| tokens[i + 1..].join(" "));
+    Some(GuiCommand::SetOption { name, value })
+}
+
+fn parse_position(tokens: &[&str]) -> Option<GuiCommand> {
+    // position [startpos | fen <fenstring>] [moves <move1> ... <movei>]
+    let moves_idx = tokens.iter().position(|&t| t == "moves");
+    let moves = moves_idx
+        .map(|i| tokens[i + 1..].iter().map(|s| s.to_string()).collect())
+        .unwrap_or_default();
+
+    match *tokens.get(1)? {
+        "startpos" => Some(GuiCommand::Position {
+            startpos: true,
+            fen: None,
+            moves,
+        }),
+        "fen" => {
+            let fen_end = moves_idx.unwrap_or(tokens.len());
+            if fen_end <= 2 {
+                return None;
+            }
+            Some(GuiCommand::Position {
+                startpos: false,
+                fen: Some(tokens[2..fen_end].join(" ")),
+                moves,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn parse_go(tokens: &[&str]) -> GoParams {
+    let mut go = GoParams::default();
+    let mut i = 1;
+    while i < tokens.len() {
+        match tokens[i] {
+            "depth" => {
+                go.depth = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "nodes" => {
+                go.nodes = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "movetime" => {
+                go.movetime = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "wtime" => {
+                go.wtime = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "btime" => {
+                go.btime = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "winc" => {
+                go.winc = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "binc" => {
+                go.binc = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "movestogo" => {
+                go.movestogo = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "infinite" => {
+                go.infinite = true;
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    go
+}
+
+impl fmt::Display for GuiCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuiCommand::Uci => write!(f, "uci"),
+            GuiCommand::UciNewGame => write!(f, "ucinewgame"),
+            GuiCommand::IsReady => write!(f, "isready"),
+            GuiCommand::Quit => write!(f, "quit"),
+            GuiCommand::SetOption { name, value } => {
+                write!(f, "setoption name {name}")?;
+                if let Some(value) = value {
+                    write!(f, " value {value}")?;
+                }
+                Ok(())
+            }
+            GuiCommand::Position {
+                startpos,
+                fen,
+                moves,
+            } => {
+                write!(f, "position")?;
+                if *startpos {
+                    write!(f, " startpos")?;
+                } else if let Some(fen) = fen {
+                    write!(f, " fen {fen}")?;
+                }
+                if !moves.is_empty() {
+                    write!(f, " moves {}", moves.join(" "))?;
+                }
+                Ok(())
+            }
+            GuiCommand::Go(go) => write!(f, "go{go}"),
+        }
+    }
+}
+
+impl fmt::Display for GoParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(depth) = self.depth {
+            write!(f, " depth {depth}")?;
+        }
+        if let Some(nodes) = self.nodes {
+            write!(f, " nodes {nodes}")?;
+        }
+        if let Some(movetime) = self.movetime {
+            write!(f, " movetime {movetime}")?;
+        }
+        if let Some(wtime) = self.wtime {
+            write!(f, " wtime {wtime}")?;
+        }
+        if let Some(btime) = self.btime {
+            write!(f, " btime {btime}")?;
+        }
+        if let Some(winc) = self.winc {
+            write!(f, " winc {winc}")?;
+        }
+        if let Some(binc) = self.binc {
+            write!(f, " binc {binc}")?;
+        }
+        if let Some(movestogo) = self.movestogo {
+            write!(f, " movestogo {movestogo}")?;
+        }
+        if self.infinite {
+            write!(f, " infinite")?;
+        }
+        Ok(())
+    }
+}
+
+/// A message sent from the engine to the GUI.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineMessage {
+    IdName(String),
+    IdAuthor(String),
+    UciOk,
+    ReadyOk,
+    Option(EngineOption),
+    Info(InfoParams),
+    BestMove { mv: String, ponder: Option<String> },
+}
+
+/// One `option` declaration advertised during engine startup.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EngineOption {
+    pub name: String,
+    pub opt_type: String,
+    pub default: Option<String>,
+    pub min: Option<i64>,
+    pub max: Option<i64>,
+    pub vars: Vec<String>,
+}
+
+/// Search progress carried by an `info` line. Every field is independently
+/// optional since engines report them incrementally across several lines.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InfoParams {
+    pub depth: Option<u32>,
+    pub score: Option<i32>,
+    pub mate: Option<i32>,
+    pub nodes: Option<u64>,
+    pub nps: Option<u64>,
+    pub time: Option<u64>,
+    pub multipv: Option<u32>,
+    pub pv: Vec<String>,
+}
+
+impl EngineMessage {
+    pub fn parse(line: &str) -> Option<Self> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match *tokens.first()? {
+            "uciok" => Some(EngineMessage::UciOk),
+            "readyok" => Some(EngineMessage::ReadyOk),
+            "id" => parse_id(&tokens),
+            "option" => parse_option(&tokens),
+            "info" => parse_info(&tokens),
+            "bestmove" => parse_bestmove(&tokens),
+            _ => None,
+        }
+    }
+}
+
+fn parse_id(tokens: &[&str]) -> Option<EngineMessage> {
+    match *tokens.get(1)? {
+        "name" => Some(EngineMessage::IdName(tokens[2..].join(" "))),
+        "author" => Some(EngineMessage::IdAuthor(tokens[2..].join(" "))),
+        _ => None,
+    }
+}
+
+const OPTION_KEYWORDS: [&str; 4] = ["default", "min", "max", "var"];
+
+fn take_until_keyword(tokens: &[&str], start: usize) -> (String, usize) {
+    let end = tokens[start..]
+        .iter()
+        .position(|t| OPTION_KEYWORDS.contains(t))
+        .map(|p| start + p)
+        .unwrap_or(tokens.len());
+    (tokens[start..end].join(" "), end)
+}
+
+fn parse_option(tokens: &[&str]) -> Option<EngineMessage> {
+    // option name <id> type <t> [default <x>] [min <x>] [max <x>] [var <x>]*
+    if tokens.get(1) != Some(&"name") {
+        return None;
+    }
+    let type_idx = tokens.iter().position(|&t| t == "type")?;
+    let name = tokens[2..type_idx].join(" ");
+
+    let mut opt = EngineOption {
+        name,
+        ..Default::default()
+    };
+
+    let mut i = type_idx;
+    while i < tokens.len() {
+        match tokens[i] {
+            "type" => {
+                opt.opt_type = tokens.get(i + 1).copied().unwrap_or_default().to_string();
+                i += 2;
+            }
+            "default" => {
+                let (val, next) = take_until_keyword(tokens, i + 1);
+                opt.default = Some(val);
+                i = next;
+            }
+            "min" => {
+                opt.min = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "max" => {
+                opt.max = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "var" => {
+                let (val, next) = take_until_keyword(tokens, i + 1);
+                opt.vars.push(val);
+                i = next;
+            }
+            _ => i += 1,
+        }
+    }
+
+    Some(EngineMessage::Option(opt))
+}
+
+fn parse_info(tokens: &[&str]) -> Option<EngineMessage> {
+    let mut info = InfoParams::default();
+    let mut i = 1;
+    while i < tokens.len() {
+        match tokens[i] {
+            "depth" => {
+                info.depth = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "multipv" => {
+                info.multipv = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "nodes" => {
+                info.nodes = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "nps" => {
+                info.nps = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "time" => {
+                info.time = tokens.get(i + 1).and_then(|v| v.parse().ok());
+                i += 2;
+            }
+            "score" => match tokens.get(i + 1).copied() {
+                Some("cp") => {
+                    info.score = tokens.get(i + 2).and_then(|v| v.parse().ok());
+                    i += 3;
+                }
+                Some("mate") => {
+                    info.mate = tokens.get(i + 2).and_then(|v| v.parse().ok());
+                    i += 3;
+                }
+                _ => i += 1,
+            },
+            "pv" => {
+                info.pv = tokens[i + 1..].iter().map(|s| s.to_string()).collect();
+                i = tokens.len();
+            }
+            _ => i += 1,
+        }
+    }
+    Some(EngineMessage::Info(info))
+}
+
+fn parse_bestmove(tokens: &[&str]) -> Option<EngineMessage> {
+    let mv = tokens.get(1).copied()?.to_string();
+    let ponder = if tokens.get(2) == Some(&"ponder") {
+        tokens.get(3).map(|s| s.to_string())
+    } else {
+        None
+    };
+    Some(EngineMessage::BestMove { mv, ponder })
+}
+
+impl fmt::Display for EngineMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EngineMessage::IdName(name) => write!(f, "id name {name}"),
+            EngineMessage::IdAuthor(author) => write!(f, "id author {author}"),
+            EngineMessage::UciOk => write!(f, "uciok"),
+            EngineMessage::ReadyOk => write!(f, "readyok"),
+            EngineMessage::Option(opt) => {
+                write!(f, "option name {} type {}", opt.name, opt.opt_type)?;
+                if let Some(default) = &opt.default {
+                    write!(f, " default {default}")?;
+                }
+                if let Some(min) = opt.min {
+                    write!(f, " min {min}")?;
+                }
+                if let Some(max) = opt.max {
+                    write!(f, " max {max}")?;
+                }
+                for var in &opt.vars {
+                    write!(f, " var {var}")?;
+                }
+                Ok(())
+            }
+            EngineMessage::Info(info) => {
+                write!(f, "info")?;
+                if let Some(depth) = info.depth {
+                    write!(f, " depth {depth}")?;
+                }
+                if let Some(multipv) = info.multipv {
+                    write!(f, " multipv {multipv}")?;
+                }
+                if let Some(score) = info.score {
+                    write!(f, " score cp {score}")?;
+                }
+                if let Some(mate) = info.mate {
+                    write!(f, " score mate {mate}")?;
+                }
+                if let Some(nodes) = info.nodes {
+                    write!(f, " nodes {nodes}")?;
+                }
+                if let Some(nps) = info.nps {
+                    write!(f, " nps {nps}")?;
+                }
+                if let Some(time) = info.time {
+                    write!(f, " time {time}")?;
+                }
+                if !info.pv.is_empty() {
+                    write!(f, " pv {}", info.pv.join(" "))?;
+                }
+                Ok(())
+            }
+            EngineMessage::BestMove { mv, ponder } => {
+                write!(f, "bestmove {mv}")?;
+                if let Some(ponder) = ponder {
+                    write!(f, " ponder {ponder}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
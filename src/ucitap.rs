@@ -1,14 +1,34 @@
+mod uci;
+
+use std::collections::BTreeMap;
 use std::fs::{self, OpenOptions};
-use std::io::{self, Read, Write};
+use std::io::{self, BufRead, BufReader, Write};
 use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use serde::Deserialize;
+use uci::{EngineMessage, GuiCommand};
 
 #[derive(Deserialize)]
 struct Config {
     engine: String,
     logfile: String,
+    /// `setoption name <key> value <value>` lines injected once the engine
+    /// reports `uciok`, before the GUI's first `isready` is forwarded.
+    /// A `BTreeMap` so injection order is deterministic (keyed by name).
+    #[serde(default)]
+    options: Option<BTreeMap<String, String>>,
+    /// Ceiling for `setoption name UCI_Elo value N` requests from the GUI.
+    /// When set, the proxy clamps N and enables `UCI_LimitStrength`.
+    #[serde(default)]
+    uci_elo_limit: Option<u32>,
+}
+
+/// Handshake state shared between the stdin and stdout relay threads.
+#[derive(Default)]
+struct HandshakeState {
+    uciok_seen: bool,
+    options_injected: bool,
 }
 
 fn main() {
@@ -21,17 +41,18 @@ fn main() {
         .expect(&format!("failed to read {}", config_file));
     let cfg: Config = serde_json::from_str(&cfg_data)
         .expect("failed to parse config");
+    let cfg = Arc::new(cfg);
 
     // Open log file (append, create if missing)
     let logfile = OpenOptions::new()
         .create(true)
         .append(true)
-        .open(cfg.logfile)
+        .open(&cfg.logfile)
         .expect("failed to open logfile");
     let logfile = Arc::new(Mutex::new(logfile));
 
     // Spawn engine with platform-specific settings
-    let mut cmd = Command::new(cfg.engine);
+    let mut cmd = Command::new(&cfg.engine);
     cmd.stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::null());
@@ -46,46 +67,68 @@ fn main() {
 
     let mut child = cmd.spawn().expect("failed to spawn engine");
 
-    let mut engine_stdin = child.stdin.take().expect("engine stdin");
-    let mut engine_stdout = child.stdout.take().expect("engine stdout");
+    let engine_stdin = child.stdin.take().expect("engine stdin");
+    let engine_stdout = child.stdout.take().expect("engine stdout");
+
+    let handshake = Arc::new(Mutex::new(HandshakeState::default()));
 
     // Thread: stdin -> engine stdin
     let log_in = Arc::clone(&logfile);
+    let cfg_in = Arc::clone(&cfg);
+    let handshake_in = Arc::clone(&handshake);
     let stdin_thread = thread::spawn(move || {
-        let mut stdin = io::stdin();
-        let mut buf = [0u8; 4096];
+        let mut engine_stdin = engine_stdin;
+        let mut reader = BufReader::new(io::stdin());
+        let mut buf = Vec::new();
         loop {
-            let n = match stdin.read(&mut buf) {
-                Ok(0) => break,
+            buf.clear();
+            let n = match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
                 Ok(n) => n,
-                Err(_) => break,
             };
-            if engine_stdin.write_all(&buf[..n]).is_err() {
-                break;
-            }
-            let _ = engine_stdin.flush();
-            if let Ok(mut log) = log_in.lock() {
-                let _ = log.write_all(&buf[..n]);
-                let _ = log.flush();
+
+            for out in rewrite_gui_line(&buf[..n], &cfg_in, &handshake_in) {
+                if engine_stdin.write_all(&out).is_err() {
+                    return;
+                }
+                let _ = engine_stdin.flush();
+                if let Ok(mut log) = log_in.lock() {
+                    let _ = log.write_all(&out);
+                    let _ = log.flush();
+                }
             }
         }
     });
 
     // Thread: engine stdout -> stdout
     let log_out = Arc::clone(&logfile);
+    let handshake_out = Arc::clone(&handshake);
     let stdout_thread = thread::spawn(move || {
+        let mut reader = BufReader::new(engine_stdout);
         let mut stdout = io::stdout();
-        let mut buf = [0u8; 4096];
+        let mut buf = Vec::new();
         loop {
-            let n = match engine_stdout.read(&mut buf) {
-                Ok(0) => break,
+            buf.clear();
+            let n = match reader.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => break,
                 Ok(n) => n,
-                Err(_) => break,
             };
-            let _ = stdout.write_all(&buf[..n]);
+            let raw = &buf[..n];
+
+            if let Ok(line) = std::str::from_utf8(raw) {
+                if EngineMessage::parse(line.trim()) == Some(EngineMessage::UciOk) {
+                    if let Ok(mut state) = handshake_out.lock() {
+                        state.uciok_seen = true;
+                    }
+                }
+            }
+
+            if stdout.write_all(raw).is_err() {
+                break;
+            }
             let _ = stdout.flush();
             if let Ok(mut log) = log_out.lock() {
-                let _ = log.write_all(&buf[..n]);
+                let _ = log.write_all(raw);
                 let _ = log.flush();
             }
         }
@@ -96,6 +139,82 @@ fn main() {
     let _ = child.wait();
 }
 
+/// Rewrites a single line from the GUI into the byte sequences that should
+/// actually be forwarded to the engine, injecting config-driven `setoption`
+/// lines ahead of the first `isready` and clamping `UCI_Elo` to
+/// `uci_elo_limit`. `raw` includes its own trailing newline; lines that are
+/// not valid UTF-8, or that don't match a rewritten command, are passed
+/// through byte-for-byte so a decode failure never drops or hangs the relay.
+fn rewrite_gui_line(raw: &[u8], cfg: &Config, handshake: &Mutex<HandshakeState>) -> Vec<Vec<u8>> {
+    let Ok(line) = std::str::from_utf8(raw) else {
+        return vec![raw.to_vec()];
+    };
+    let line = line.trim_end_matches(['\n', '\r']);
+
+    let lines: Vec<String> = match GuiCommand::parse(line) {
+        Some(GuiCommand::IsReady) => {
+            let mut out = Vec::new();
+            let mut should_inject = false;
+            if let Ok(mut state) = handshake.lock() {
+                should_inject = state.uciok_seen && !state.options_injected;
+                if should_inject {
+                    state.options_injected = true;
+                }
+            }
+            if should_inject {
+                if let Some(options) = &cfg.options {
+                    for (name, value) in options {
+                        out.push(
+                            GuiCommand::SetOption {
+                                name: name.clone(),
+                                value: Some(value.clone()),
+                            }
+                            .to_string(),
+                        );
+                    }
+                }
+            }
+            out.push(line.to_string());
+            out
+        }
+        Some(GuiCommand::SetOption { name, value }) if name.eq_ignore_ascii_case("UCI_Elo") => {
+            let mut out = Vec::new();
+            if let (Some(limit), Some(requested)) =
+                (cfg.uci_elo_limit, value.as_deref().and_then(|v| v.parse::<u32>().ok()))
+            {
+                let clamped = requested.min(limit);
+                out.push(
+                    GuiCommand::SetOption {
+                        name: "UCI_LimitStrength".to_string(),
+                        value: Some("true".to_string()),
+                    }
+                    .to_string(),
+                );
+                out.push(
+                    GuiCommand::SetOption {
+                        name,
+                        value: Some(clamped.to_string()),
+                    }
+                    .to_string(),
+                );
+            } else {
+                out.push(line.to_string());
+            }
+            out
+        }
+        _ => return vec![raw.to_vec()],
+    };
+
+    lines
+        .into_iter()
+        .map(|l| {
+            let mut bytes = l.into_bytes();
+            bytes.push(b'\n');
+            bytes
+        })
+        .collect()
+}
+
 // Parse --config argument from command line
 fn parse_config_arg(args: &[String]) -> Option<String> {
     for i in 0..args.len() {
@@ -104,4 +223,4 @@ fn parse_config_arg(args: &[String]) -> Option<String> {
         }
     }
     None
-}
\ No newline at end of file
+}
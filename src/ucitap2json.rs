@@ -1,14 +1,17 @@
+mod uci;
+
 use clap::Parser;
-use regex::Regex;
 use serde::Serialize;
-use shakmaty::{Chess, Position, Square, Role};
+use shakmaty::{Chess, Color, Position, Square, Role};
 use shakmaty::fen::Fen;
 use shakmaty::san::San;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 use zstd::stream::write::Encoder;
 use anyhow::Result;
+use uci::{EngineMessage, GuiCommand};
 
 #[derive(Parser)]
 struct Args {
@@ -19,13 +22,61 @@ struct Args {
     /// Compress output to .zst
     #[arg(short = 'c')]
     compress: bool,
+
+    /// Output format: json, pgn, or tree
+    #[arg(short = 'f', long = "format", default_value = "json")]
+    format: String,
 }
 
 #[derive(Serialize)]
 struct Payload {
     engine: String,
     fen: String,
-    ply: Option<u32>,
+    /// UCI moves played from `start_fen` (or startpos) to reach `fen`.
+    moves: Option<String>,
+    /// Starting FEN for `moves`, or `None` when the game started from the standard position.
+    start_fen: Option<String>,
+    /// One entry per MultiPV slot, ordered by `multipv`, holding the latest
+    /// values reported for that slot before `bestmove` (`info` lines without
+    /// `multipv` count as slot 1). Each field keeps the last value any `info`
+    /// line gave it, so the fields of an entry need not come from one line.
+    lines: Vec<PvLine>,
+    /// Game-clock state taken from the `go` command that triggered this search, if any.
+    clock: Option<PayloadClock>,
+}
+
+#[derive(Serialize, Clone)]
+struct PayloadClock {
+    /// Side-to-move's remaining time when `go` was issued.
+    remaining_ms: u64,
+    /// Side-to-move's increment per move.
+    increment_ms: u64,
+    movestogo: Option<u32>,
+    /// How long the side actually spent on *this* move: this move's `remaining_ms`
+    /// plus increment, minus `remaining_ms` reported on the side's next `go`.
+    /// Filled in once that next `go` arrives, so the side's final move in the
+    /// log (if any) is left `None`.
+    time_spent_ms: Option<u64>,
+}
+
+/// Base time and increment observed on the first `go` command for each side.
+#[derive(Serialize, Default)]
+struct TimeControlSummary {
+    base_time_ms: Option<u64>,
+    white_increment_ms: Option<u64>,
+    black_increment_ms: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Output {
+    time_control: TimeControlSummary,
+    positions: Vec<Payload>,
+}
+
+#[derive(Serialize, Clone, Default)]
+struct PvLine {
+    multipv: u32,
+    depth: Option<u32>,
     score: Option<i32>,
     mate: Option<i32>,
     nodes: Option<u64>,
@@ -34,6 +85,31 @@ struct Payload {
     pv: Option<String>,
 }
 
+impl Payload {
+    /// The primary (MultiPV 1) line, used where callers only care about a single eval.
+    fn best_line(&self) -> Option<&PvLine> {
+        self.lines.iter().find(|l| l.multipv == 1).or_else(|| self.lines.first())
+    }
+}
+
+/// One played move in a reconstructed game tree, with the engine's candidate
+/// lines at that point hanging off it as `variations`.
+#[derive(Serialize)]
+struct GameNode {
+    san: Option<String>,
+    fen: String,
+    eval: Option<NodeEval>,
+    children: Vec<GameNode>,
+    variations: Vec<Vec<String>>,
+}
+
+#[derive(Serialize)]
+struct NodeEval {
+    depth: Option<u32>,
+    score: Option<i32>,
+    mate: Option<i32>,
+}
+
 fn trim_fen(fen: &str) -> String {
     let parts: Vec<&str> = fen.split_whitespace().collect();
     if parts.len() >= 4 {
@@ -107,9 +183,238 @@ fn apply_moves(mut board: Chess, moves_str: &str) -> Chess {
     board
 }
 
+/// Formats the engine's best-line evaluation as a PGN move-comment, e.g. `{+0.34/20 12.3s} {Nf3 Nc6}`.
+fn eval_comment(p: &Payload) -> String {
+    let Some(line) = p.best_line() else {
+        return String::new();
+    };
+
+    let eval_str = match (line.mate, line.score) {
+        (Some(m), _) => format!("#{m}"),
+        (None, Some(cp)) => format!("{:+.2}", cp as f64 / 100.0),
+        (None, None) => return String::new(),
+    };
+
+    let depth_str = line.depth.map(|d| format!("/{d}")).unwrap_or_default();
+    let time_str = line
+        .time
+        .map(|t| format!(" {:.1}s", t as f64 / 1000.0))
+        .unwrap_or_default();
+
+    let mut comment = format!("{{{eval_str}{depth_str}{time_str}}}");
+
+    if let Some(pv) = &line.pv {
+        if !pv.is_empty() {
+            comment.push_str(&format!(" {{{pv}}}"));
+        }
+    }
+
+    comment
+}
+
+/// Reconstructs a single `Payload` as a standalone PGN game: the mainline is the
+/// `position ... moves ...` sequence replayed from `start_fen` (or startpos), with the
+/// engine's evaluation and best line attached as a comment after the final move.
+fn payload_to_pgn(p: &Payload, game_no: usize) -> String {
+    let mut board = match &p.start_fen {
+        Some(fen_str) => fen_str
+            .parse::<Fen>()
+            .ok()
+            .and_then(|f| f.into_position(shakmaty::CastlingMode::Standard).ok())
+            .unwrap_or_default(),
+        None => Chess::default(),
+    };
+
+    let mut tags = String::new();
+    tags.push_str("[Event \"ucitap analysis\"]\n");
+    tags.push_str("[Site \"?\"]\n");
+    tags.push_str("[Date \"????.??.??\"]\n");
+    tags.push_str(&format!("[Round \"{game_no}\"]\n"));
+    tags.push_str("[White \"?\"]\n");
+    tags.push_str("[Black \"?\"]\n");
+    tags.push_str("[Result \"*\"]\n");
+    if let Some(fen_str) = &p.start_fen {
+        tags.push_str("[SetUp \"1\"]\n");
+        tags.push_str(&format!("[FEN \"{fen_str}\"]\n"));
+    }
+    if !p.engine.is_empty() {
+        tags.push_str(&format!("[Annotator \"{}\"]\n", p.engine));
+    }
+
+    let uci_moves: Vec<&str> = p.moves.as_deref().unwrap_or("").split_whitespace().collect();
+
+    let mut movetext = String::new();
+    let mut white_to_move = board.turn() == Color::White;
+    for (i, uci) in uci_moves.iter().enumerate() {
+        let Some(chess_move) = parse_uci_move(uci, &board) else {
+            break;
+        };
+
+        if white_to_move {
+            movetext.push_str(&format!("{}. ", board.fullmoves()));
+        } else if i == 0 {
+            movetext.push_str(&format!("{}... ", board.fullmoves()));
+        }
+
+        let san = San::from_move(&board, chess_move);
+        movetext.push_str(&san.to_string());
+        movetext.push(' ');
+        board.play_unchecked(chess_move);
+
+        if i + 1 == uci_moves.len() {
+            let comment = eval_comment(p);
+            if !comment.is_empty() {
+                movetext.push_str(&comment);
+                movetext.push(' ');
+            }
+        }
+
+        white_to_move = !white_to_move;
+    }
+
+    if uci_moves.is_empty() {
+        let comment = eval_comment(p);
+        if !comment.is_empty() {
+            movetext.push_str(&comment);
+            movetext.push(' ');
+        }
+    }
+
+    movetext.push_str("*\n");
+
+    format!("{tags}\n{movetext}")
+}
+
+/// Groups captured positions into games: a payload extends the previous game
+/// when it shares the same `start_fen` and its move list is the previous
+/// payload's move list with one or more extra moves appended.
+fn group_games(results: &[Payload]) -> Vec<Vec<&Payload>> {
+    let mut games: Vec<Vec<&Payload>> = Vec::new();
+
+    for p in results {
+        let tokens: Vec<&str> = p.moves.as_deref().unwrap_or("").split_whitespace().collect();
+
+        let extends_last = games.last().and_then(|g| g.last()).is_some_and(|prev| {
+            let prev_tokens: Vec<&str> = prev.moves.as_deref().unwrap_or("").split_whitespace().collect();
+            prev.start_fen == p.start_fen
+                && tokens.len() > prev_tokens.len()
+                && tokens[..prev_tokens.len()] == prev_tokens[..]
+        });
+
+        if extends_last {
+            games.last_mut().unwrap().push(p);
+        } else {
+            games.push(vec![p]);
+        }
+    }
+
+    games
+}
+
+/// Pulls a node's eval and candidate variations out of the payload whose
+/// analysis applies to it.
+fn node_eval_and_variations(p: &Payload) -> (Option<NodeEval>, Vec<Vec<String>>) {
+    (
+        p.best_line().map(|l| NodeEval {
+            depth: l.depth,
+            score: l.score,
+            mate: l.mate,
+        }),
+        p.lines
+            .iter()
+            .filter_map(|l| l.pv.as_ref())
+            .map(|pv| pv.split_whitespace().map(str::to_string).collect())
+            .collect(),
+    )
+}
+
+/// Reconstructs one game as a move tree: the mainline is the actually played
+/// moves (taken from the longest move list in the group), with the analyzed
+/// PVs at each ply hung off that node as `variations`. The root node carries
+/// the opening-position analysis, if the group has a zero-move payload for it.
+fn build_game_tree(payloads: &[&Payload]) -> GameNode {
+    let last = payloads.last().expect("game group is non-empty");
+
+    let mut board = match &last.start_fen {
+        Some(fen_str) => fen_str
+            .parse::<Fen>()
+            .ok()
+            .and_then(|f| f.into_position(shakmaty::CastlingMode::Standard).ok())
+            .unwrap_or_default(),
+        None => Chess::default(),
+    };
+    let root_fen = trim_fen(&Fen::from_position(&board, shakmaty::EnPassantMode::Legal).to_string());
+
+    let moves: Vec<&str> = last.moves.as_deref().unwrap_or("").split_whitespace().collect();
+
+    let mut nodes: Vec<GameNode> = Vec::new();
+    for (ply, uci) in moves.iter().enumerate() {
+        let Some(chess_move) = parse_uci_move(uci, &board) else {
+            break;
+        };
+        let san = San::from_move(&board, chess_move).to_string();
+        board.play_unchecked(chess_move);
+        let fen_after = trim_fen(&Fen::from_position(&board, shakmaty::EnPassantMode::Legal).to_string());
+
+        // The payload whose analysis was captured right after this move, if any.
+        let analyzed = payloads.iter().find(|p| {
+            p.moves
+                .as_deref()
+                .map(|m| m.split_whitespace().count())
+                .unwrap_or(0)
+                == ply + 1
+        });
+
+        let (eval, variations) = analyzed
+            .map(|p| node_eval_and_variations(p))
+            .unwrap_or((None, Vec::new()));
+
+        nodes.push(GameNode {
+            san: Some(san),
+            fen: fen_after,
+            eval,
+            children: Vec::new(),
+            variations,
+        });
+    }
+
+    let mut children = Vec::new();
+    for mut node in nodes.into_iter().rev() {
+        node.children = children;
+        children = vec![node];
+    }
+
+    // The opening position's own analysis, if it was captured as a zero-move payload.
+    let root_analyzed = payloads.iter().find(|p| {
+        p.moves
+            .as_deref()
+            .map(|m| m.split_whitespace().count())
+            .unwrap_or(0)
+            == 0
+    });
+    let (root_eval, root_variations) = root_analyzed
+        .map(|p| node_eval_and_variations(p))
+        .unwrap_or((None, Vec::new()));
+
+    GameNode {
+        san: None,
+        fen: root_fen,
+        eval: root_eval,
+        children,
+        variations: root_variations,
+    }
+}
+
 fn main() -> Result<()> {
     let args = Args::parse();
 
+    if !["json", "pgn", "tree"].contains(&args.format.as_str()) {
+        anyhow::bail!(
+            "unknown --format {:?}, expected \"json\", \"pgn\", or \"tree\"",
+            args.format
+        );
+    }
+
     let input = File::open(&args.log)?;
     let reader = BufReader::new(input);
 
@@ -119,29 +424,36 @@ fn main() -> Result<()> {
         .to_string_lossy()
         .to_string();
 
-    let json_path = format!("{base}.json");
+    let ext = match args.format.as_str() {
+        "pgn" => "pgn",
+        _ => "json",
+    };
+    let out_path = format!("{base}.{ext}");
     let final_path = if args.compress {
         format!("{base}.zst")
     } else {
-        json_path.clone()
+        out_path.clone()
     };
 
     println!("📖 Parsing UCI log: {}", args.log);
 
-    let re_info = Regex::new(r"info ").unwrap();
-    let re_pv = Regex::new(r"(^| )pv (.*)$").unwrap();
-
     let mut board = Chess::default();
     let mut engine = String::new();
 
     let mut fen: Option<String> = None;
-    let mut ply: Option<u32> = None;
-    let mut score: Option<i32> = None;
-    let mut mate: Option<i32> = None;
-    let mut nodes: Option<u64> = None;
-    let mut nps: Option<u64> = None;
-    let mut time: Option<u64> = None;
-    let mut pv: Option<String> = None;
+    let mut moves: Option<String> = None;
+    let mut start_fen: Option<String> = None;
+    let mut pv_lines: BTreeMap<u32, PvLine> = BTreeMap::new();
+    let mut pending_clock: Option<PayloadClock> = None;
+    let mut pending_clock_side: Option<Color> = None;
+
+    // Per-side (remaining, increment) as of that side's previous `go`, plus the
+    // index into `results` of the payload that `go` eventually produced — used
+    // to backfill `time_spent_ms` onto that earlier move once the side's next
+    // `go` reveals how much of its remaining time was actually spent on it.
+    let mut last_go_clock: HashMap<Color, (u64, u64)> = HashMap::new();
+    let mut last_payload_idx: HashMap<Color, usize> = HashMap::new();
+    let mut time_control = TimeControlSummary::default();
 
     let mut results: Vec<Payload> = Vec::new();
     let mut line_count: u64 = 0;
@@ -154,130 +466,199 @@ fn main() -> Result<()> {
             println!("📖 Parsed {} lines…", line_count);
         }
 
-        match line.as_str() {
-            "uci" | "ucinewgame" => {
-                board = Chess::default();
-                fen = None;
-                ply = None;
-                score = None;
-                mate = None;
-                nodes = None;
-                nps = None;
-                time = None;
-                pv = None;
-                continue;
-            }
-            _ => {}
-        }
+        if let Some(cmd) = GuiCommand::parse(&line) {
+            match cmd {
+                GuiCommand::Uci | GuiCommand::UciNewGame => {
+                    board = Chess::default();
+                    fen = None;
+                    moves = None;
+                    start_fen = None;
+                    pv_lines.clear();
+                    pending_clock = None;
+                    pending_clock_side = None;
+                    last_go_clock.clear();
+                    last_payload_idx.clear();
+                }
+                GuiCommand::Position {
+                    startpos,
+                    fen: fen_str,
+                    moves: uci_moves,
+                } => {
+                    if startpos {
+                        board = Chess::default();
+                        start_fen = None;
+                    } else if let Some(fen_str) = &fen_str {
+                        if let Ok(parsed_fen) = fen_str.parse::<Fen>() {
+                            if let Ok(pos) = parsed_fen.into_position(shakmaty::CastlingMode::Standard) {
+                                board = pos;
+                                start_fen = Some(fen_str.clone());
+                            }
+                        }
+                    }
 
-        if let Some(rest) = line.strip_prefix("id name ") {
-            engine = rest.to_string();
-            continue;
-        }
+                    if uci_moves.is_empty() {
+                        moves = None;
+                    } else {
+                        let moves_str = uci_moves.join(" ");
+                        board = apply_moves(board, &moves_str);
+                        moves = Some(moves_str);
+                    }
 
-        if let Some(rest) = line.strip_prefix("position ") {
-            if rest.starts_with("startpos") {
-                board = Chess::default();
-                if let Some(moves) = rest.strip_prefix("startpos moves ") {
-                    board = apply_moves(board, moves);
+                    let f = Fen::from_position(&board, shakmaty::EnPassantMode::Legal);
+                    fen = Some(trim_fen(&f.to_string()));
                 }
-                let f = Fen::from_position(&board, shakmaty::EnPassantMode::Legal);
-                fen = Some(trim_fen(&f.to_string()));
-            } else if let Some(fen_part) = rest.strip_prefix("fen ") {
-                // Split on " moves " to handle both FEN and subsequent moves
-                if let Some(moves_idx) = fen_part.find(" moves ") {
-                    let fen_str = &fen_part[..moves_idx];
-                    let moves_str = &fen_part[moves_idx + 7..]; // Skip " moves "
-                    
-                    if let Ok(parsed_fen) = fen_str.parse::<Fen>() {
-                        if let Ok(pos) = parsed_fen.into_position(shakmaty::CastlingMode::Standard) {
-                            board = pos;
-                            board = apply_moves(board, moves_str);
-                        }
+                GuiCommand::Go(go) => {
+                    let side = board.turn();
+                    let remaining = match side {
+                        Color::White => go.wtime,
+                        Color::Black => go.btime,
+                    };
+                    let increment = match side {
+                        Color::White => go.winc,
+                        Color::Black => go.binc,
                     }
-                } else {
-                    // Just FEN, no moves
-                    if let Ok(parsed_fen) = fen_part.parse::<Fen>() {
-                        if let Ok(pos) = parsed_fen.into_position(shakmaty::CastlingMode::Standard) {
-                            board = pos;
+                    .unwrap_or(0);
+
+                    if let Some(remaining) = remaining {
+                        if time_control.base_time_ms.is_none() {
+                            time_control.base_time_ms = Some(remaining);
+                        }
+                        match side {
+                            Color::White => time_control.white_increment_ms.get_or_insert(increment),
+                            Color::Black => time_control.black_increment_ms.get_or_insert(increment),
+                        };
+
+                        // This `go`'s remaining time tells us how long the side spent on
+                        // its *previous* move (the one searched by its previous `go`), so
+                        // backfill that onto the payload `go` already produced rather than
+                        // the one we're about to start.
+                        if let (Some(&(prev_remaining, prev_increment)), Some(&idx)) =
+                            (last_go_clock.get(&side), last_payload_idx.get(&side))
+                        {
+                            let time_spent_ms = (prev_remaining + prev_increment).saturating_sub(remaining);
+                            if let Some(clock) = results[idx].clock.as_mut() {
+                                clock.time_spent_ms = Some(time_spent_ms);
+                            }
                         }
+                        last_go_clock.insert(side, (remaining, increment));
+
+                        pending_clock = Some(PayloadClock {
+                            remaining_ms: remaining,
+                            increment_ms: increment,
+                            movestogo: go.movestogo,
+                            time_spent_ms: None,
+                        });
+                        pending_clock_side = Some(side);
                     }
                 }
-                let f = Fen::from_position(&board, shakmaty::EnPassantMode::Legal);
-                fen = Some(trim_fen(&f.to_string()));
+                _ => {}
             }
             continue;
         }
 
-        if re_info.is_match(&line) {
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            let mut i = 0;
-            while i < parts.len() {
-                match parts[i] {
-                    "depth" => ply = parts.get(i + 1).and_then(|v| v.parse().ok()),
-                    "cp" => score = parts.get(i + 1).and_then(|v| v.parse().ok()),
-                    "mate" => mate = parts.get(i + 1).and_then(|v| v.parse().ok()),
-                    "nodes" => nodes = parts.get(i + 1).and_then(|v| v.parse().ok()),
-                    "nps" => nps = parts.get(i + 1).and_then(|v| v.parse().ok()),
-                    "time" => time = parts.get(i + 1).and_then(|v| v.parse().ok()),
-                    _ => {}
+        if let Some(msg) = EngineMessage::parse(&line) {
+            match msg {
+                EngineMessage::IdName(name) => engine = name,
+                EngineMessage::Info(info) => {
+                    let idx = info.multipv.unwrap_or(1);
+                    let line = pv_lines.entry(idx).or_insert_with(|| PvLine {
+                        multipv: idx,
+                        ..Default::default()
+                    });
+                    if info.depth.is_some() {
+                        line.depth = info.depth;
+                    }
+                    if info.score.is_some() {
+                        line.score = info.score;
+                    }
+                    if info.mate.is_some() {
+                        line.mate = info.mate;
+                    }
+                    if info.nodes.is_some() {
+                        line.nodes = info.nodes;
+                    }
+                    if info.nps.is_some() {
+                        line.nps = info.nps;
+                    }
+                    if info.time.is_some() {
+                        line.time = info.time;
+                    }
+                    if !info.pv.is_empty() {
+                        let uci_pv = info.pv.join(" ");
+                        line.pv = Some(convert_pv_to_san(&uci_pv, &board));
+                    }
                 }
-                i += 1;
-            }
-
-            if let Some(cap) = re_pv.captures(&line) {
-                let uci_pv = cap[2].trim();
-                // Convert UCI PV to SAN
-                let san_pv = convert_pv_to_san(uci_pv, &board);
-                pv = Some(san_pv);
-            }
+                EngineMessage::BestMove { .. } => {
+                    let clock = pending_clock.take();
+                    let clock_side = pending_clock_side.take();
 
-            continue;
-        }
+                    if let Some(fen_val) = &fen {
+                        results.push(Payload {
+                            engine: engine.clone(),
+                            fen: fen_val.clone(),
+                            moves: moves.clone(),
+                            start_fen: start_fen.clone(),
+                            lines: pv_lines.values().cloned().collect(),
+                            clock,
+                        });
+                        if let Some(side) = clock_side {
+                            last_payload_idx.insert(side, results.len() - 1);
+                        }
+                    }
 
-        if line.starts_with("bestmove") {
-            if let Some(fen_val) = &fen {
-                results.push(Payload {
-                    engine: engine.clone(),
-                    fen: fen_val.clone(),
-                    ply,
-                    score,
-                    mate,
-                    nodes,
-                    nps,
-                    time,
-                    pv: pv.clone(),
-                });
+                    pv_lines.clear();
+                }
+                _ => {}
             }
-
-            ply = None;
-            score = None;
-            mate = None;
-            nodes = None;
-            nps = None;
-            time = None;
-            pv = None;
         }
     }
 
     println!("✅ Parsing complete — {} positions captured", results.len());
-    println!("🧠 Serializing JSON objects…");
 
-    let json_data = serde_json::to_vec_pretty(&results)?;
-
-    println!("✅ JSON serialization complete");
+    let out_data = match args.format.as_str() {
+        "pgn" => {
+            println!("♟️  Rendering PGN games…");
+            let pgn = results
+                .iter()
+                .enumerate()
+                .map(|(i, p)| payload_to_pgn(p, i + 1))
+                .collect::<Vec<_>>()
+                .join("\n");
+            println!("✅ PGN rendering complete");
+            pgn.into_bytes()
+        }
+        "tree" => {
+            println!("🌳 Reconstructing game trees…");
+            let games = group_games(&results);
+            let trees: Vec<GameNode> = games.iter().map(|g| build_game_tree(g)).collect();
+            let json_data = serde_json::to_vec_pretty(&trees)?;
+            println!("✅ Game tree reconstruction complete — {} games", trees.len());
+            json_data
+        }
+        _ => {
+            println!("🧠 Serializing JSON objects…");
+            let output = Output {
+                time_control,
+                positions: results,
+            };
+            let json_data = serde_json::to_vec_pretty(&output)?;
+            println!("✅ JSON serialization complete");
+            results = output.positions;
+            json_data
+        }
+    };
 
     if args.compress {
         println!("🗜️  Compressing JSON (max level)…");
         let file = File::create(&final_path)?;
         let mut encoder = Encoder::new(file, 22)?;
-        encoder.write_all(&json_data)?;
+        encoder.write_all(&out_data)?;
         encoder.finish()?;
         println!("💾 Writing output file: {}", final_path);
         println!("🎉 Done! Wrote {} positions", results.len());
     } else {
         println!("💾 Writing output file: {}", final_path);
-        std::fs::write(&final_path, json_data)?;
+        std::fs::write(&final_path, out_data)?;
         println!("🎉 Done! Wrote {} positions", results.len());
     }
 